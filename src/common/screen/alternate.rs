@@ -8,8 +8,11 @@
 use super::commands::{self, IAlternateScreenCommand};
 use super::{RawScreen, Screen, TerminalOutput};
 use common::functions;
+use std::cell::RefCell;
 use std::convert::From;
+use std::fmt;
 use std::io;
+use std::io::Write;
 
 /// With this type you will be able to switch to alternate screen and back to main screen.
 /// Check also the Screen type for swishing to alternate mode.
@@ -18,12 +21,19 @@ use std::io;
 pub struct AlternateScreen {
     command: Box<IAlternateScreenCommand + Sync + Send>,
     pub screen: Screen,
+    restore_error_handler: Option<Box<FnMut(io::Error) + Send>>,
+    closed: bool,
 }
 
 impl AlternateScreen {
     /// Create new instance of alternate screen.
     pub fn new(command: Box<IAlternateScreenCommand + Sync + Send>, screen: Screen) -> Self {
-        AlternateScreen { command, screen }
+        AlternateScreen {
+            command,
+            screen,
+            restore_error_handler: None,
+            closed: false,
+        }
     }
 
     /// Switch to alternate screen. This function will return an `AlternateScreen` instance if everything went well this type will give you control over the `AlternateScreen`.
@@ -67,11 +77,269 @@ impl AlternateScreen {
         self.command.disable(&self.screen.stdout)?;
         Ok(())
     }
+
+    /// Re-enable the alternate screen after a previous call to `to_main_screen`. Lets a held
+    /// `AlternateScreen` flip back and forth between buffers any number of times, while `Drop`
+    /// still guarantees the final restore to the main screen.
+    // Named `enable_alternate_screen` rather than `to_alternate_screen` to avoid clashing with
+    // the static constructor above; see CHANGELOG.md.
+    pub fn enable_alternate_screen(&mut self) -> io::Result<()> {
+        self.command.enable(&mut self.screen.stdout)?;
+        Ok(())
+    }
+
+    /// Register a callback invoked from `Drop` if the final restore to the main screen fails.
+    pub fn on_restore_error(&mut self, handler: Box<FnMut(io::Error) + Send>) {
+        self.restore_error_handler = Some(handler);
+    }
+
+    /// Consume this `AlternateScreen`, restoring the main screen and returning the restore error
+    /// directly, bypassing `Drop`.
+    pub fn close(mut self) -> io::Result<()> {
+        let result = self.to_main_screen();
+        self.closed = true;
+        result
+    }
+}
+
+impl Write for AlternateScreen {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.screen.stdout.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.screen.stdout.flush()
+    }
+}
+
+/// Writing this to any `Write` implementor switches to the alternate screen, without the
+/// caller needing to own a `Screen` or an `AlternateScreen`.
+///
+/// ```ignore
+/// write!(stdout, "{}{}", ToAlternateScreen, "hello from the alternate screen")?;
+/// ```
+///
+/// WARNING: on a legacy (non-VT) Windows console, this does *not* write any bytes to the given
+/// destination. Instead it switches the real system console directly, regardless of what `f`
+/// actually is. Formatting into a non-terminal sink (a `String`, a log buffer, a file) on such a
+/// console will still flip the live console as a side effect. Prefer `AlternateScreen` if you
+/// need this to behave correctly on legacy Windows consoles.
+#[derive(Clone, Copy, Debug)]
+pub struct ToAlternateScreen;
+
+impl fmt::Display for ToAlternateScreen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(target_os = "windows")]
+        {
+            if !functions::is_ansi_capable() {
+                switch_legacy_console(true).map_err(|_| fmt::Error)?;
+                return Ok(());
+            }
+        }
+
+        write!(f, csi!("?1049h"))
+    }
+}
+
+/// The counterpart to `ToAlternateScreen`: writing this switches back to the main screen.
+///
+/// ```ignore
+/// write!(stdout, "{}", ToMainScreen)?;
+/// ```
+///
+/// WARNING: see `ToAlternateScreen`'s doc comment — on a legacy Windows console this mutates the
+/// live system console directly instead of writing to the given destination.
+#[derive(Clone, Copy, Debug)]
+pub struct ToMainScreen;
+
+impl fmt::Display for ToMainScreen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[cfg(target_os = "windows")]
+        {
+            if !functions::is_ansi_capable() {
+                switch_legacy_console(false).map_err(|_| fmt::Error)?;
+                return Ok(());
+            }
+        }
+
+        write!(f, csi!("?1049l"))
+    }
+}
+
+#[cfg(target_os = "windows")]
+thread_local! {
+    // The same `IAlternateScreenCommand`/`TerminalOutput` pair `AlternateScreen` itself threads
+    // through `enable`/`disable`, kept alive here (per thread) across repeated `Display` writes
+    // since `fmt()` has no `AlternateScreen` to hang this state off of.
+    static LEGACY_CONSOLE_COMMAND: commands::win_commands::ToAlternateScreenCommand =
+        commands::win_commands::ToAlternateScreenCommand::new();
+    static LEGACY_CONSOLE_OUTPUT: RefCell<TerminalOutput> = RefCell::new(TerminalOutput::new());
+}
+
+/// Switches a legacy (non-VT) Windows console directly, bypassing the `std::io::Write` machinery
+/// entirely since such consoles don't understand the ANSI CSI sequence written by `Display`.
+/// Pulled out into its own function so that the side effect isn't hidden inline inside ordinary
+/// `Display` formatting.
+#[cfg(target_os = "windows")]
+fn switch_legacy_console(to_alternate: bool) -> io::Result<()> {
+    LEGACY_CONSOLE_COMMAND.with(|command| {
+        LEGACY_CONSOLE_OUTPUT.with(|stdout| {
+            if to_alternate {
+                command.enable(&mut stdout.borrow_mut())
+            } else {
+                command.disable(&stdout.borrow())
+            }
+        })
+    })
 }
 
 impl Drop for AlternateScreen {
-    /// This will switch back to main screen on drop.
+    /// This will switch back to main screen on drop, unless `close` already did so.
     fn drop(&mut self) {
-        self.to_main_screen();
+        if self.closed {
+            return;
+        }
+
+        if let Err(error) = self.to_main_screen() {
+            if let Some(ref mut handler) = self.restore_error_handler {
+                handler(error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// A command that counts how many times it was asked to enable/disable the alternate screen,
+    /// always succeeding.
+    struct CountingCommand {
+        enable_calls: Arc<AtomicU32>,
+        disable_calls: Arc<AtomicU32>,
+    }
+
+    impl IAlternateScreenCommand for CountingCommand {
+        fn enable(&self, _stdout: &mut TerminalOutput) -> io::Result<()> {
+            self.enable_calls.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn disable(&self, _stdout: &TerminalOutput) -> io::Result<()> {
+            self.disable_calls.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn screen_with(command: Box<IAlternateScreenCommand + Sync + Send>) -> AlternateScreen {
+        AlternateScreen::new(command, Screen::from(TerminalOutput::new()))
+    }
+
+    #[test]
+    fn write_and_flush_pass_through_to_the_screen_stdout() {
+        let mut screen = screen_with(Box::new(CountingCommand {
+            enable_calls: Arc::new(AtomicU32::new(0)),
+            disable_calls: Arc::new(AtomicU32::new(0)),
+        }));
+
+        screen.write(b"hello").unwrap();
+        screen.flush().unwrap();
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn to_alternate_screen_writes_the_raw_csi_sequence() {
+        assert_eq!(ToAlternateScreen.to_string(), "\u{1b}[?1049h");
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn to_main_screen_writes_the_raw_csi_sequence() {
+        assert_eq!(ToMainScreen.to_string(), "\u{1b}[?1049l");
+    }
+
+    #[test]
+    fn enable_alternate_screen_can_reenter_after_to_main_screen() {
+        let enable_calls = Arc::new(AtomicU32::new(0));
+        let disable_calls = Arc::new(AtomicU32::new(0));
+        let mut screen = screen_with(Box::new(CountingCommand {
+            enable_calls: enable_calls.clone(),
+            disable_calls: disable_calls.clone(),
+        }));
+
+        screen.to_main_screen().unwrap();
+        screen.enable_alternate_screen().unwrap();
+        drop(screen);
+
+        assert_eq!(enable_calls.load(Ordering::SeqCst), 1);
+        // `to_main_screen()` plus the final restore in `Drop`.
+        assert_eq!(disable_calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// A command whose `disable` outcome is fixed at construction, so tests can force a failing
+    /// or succeeding restore.
+    struct FixedOutcomeCommand {
+        disable_ok: bool,
+    }
+
+    impl IAlternateScreenCommand for FixedOutcomeCommand {
+        fn enable(&self, _stdout: &mut TerminalOutput) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn disable(&self, _stdout: &TerminalOutput) -> io::Result<()> {
+            if self.disable_ok {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::Other, "restore failed"))
+            }
+        }
+    }
+
+    #[test]
+    fn drop_invokes_on_restore_error_when_restore_fails() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let calls_in_handler = calls.clone();
+        let mut screen = screen_with(Box::new(FixedOutcomeCommand { disable_ok: false }));
+        screen.on_restore_error(Box::new(move |_err| {
+            calls_in_handler.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        drop(screen);
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn close_surfaces_the_error_and_suppresses_the_drop_handler() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let calls_in_handler = calls.clone();
+        let mut screen = screen_with(Box::new(FixedOutcomeCommand { disable_ok: false }));
+        screen.on_restore_error(Box::new(move |_err| {
+            calls_in_handler.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        let result = screen.close();
+
+        assert!(result.is_err());
+        // `close()` surfaces the error directly and the subsequent `Drop` must not also invoke
+        // the handler for the same failure.
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn drop_does_not_invoke_handler_on_successful_restore() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let calls_in_handler = calls.clone();
+        let mut screen = screen_with(Box::new(FixedOutcomeCommand { disable_ok: true }));
+        screen.on_restore_error(Box::new(move |_err| {
+            calls_in_handler.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        drop(screen);
+
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
     }
 }